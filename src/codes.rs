@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 pub struct ErrorDesc {
     pub short: &'static str,
     pub long: &'static str,
@@ -11,6 +13,32 @@ impl ErrorDesc {
     }
 }
 
+/// Tracks the set of `ErrorCode`s a `Reporter` has emitted, so a front-end
+/// can look one up by its `short` name and print its `long` description
+/// (rustc's `--explain E0499`).
+#[derive(Default)]
+pub struct CodeRegistry {
+    codes: BTreeMap<&'static str, ErrorCode>,
+}
+
+impl CodeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, code: ErrorCode) {
+        self.codes.insert(code.short, code);
+    }
+
+    pub fn get(&self, short: &str) -> Option<ErrorCode> {
+        self.codes.get(short).copied()
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = ErrorCode> + '_ {
+        self.codes.values().copied()
+    }
+}
+
 #[macro_export]
 macro_rules! declare_error {
     ($name:ident, $short:literal, $long:literal) => {
@@ -1,33 +1,74 @@
 use ansi_term::{Color, Style};
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{fmt, io};
 
-use crate::{reporter::Message, Loc, Reporter};
+use crate::{
+    loc::FileId,
+    reporter::{Level, Message, Span},
+    Reporter,
+};
 
-pub struct SourceInfo<'a> {
+struct SourceInfo<'a> {
     name: Option<&'a str>,
     text: &'a str,
     newlines: Vec<usize>,
 }
 
-#[derive(Clone, Copy)]
-pub enum DisplayOptions {
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
     Terminal,
     String,
+    Json,
+    /// Resolved to `Terminal` (if the output is a real tty) or `String`
+    /// (otherwise) by `extract_report_to_io`/`extract_report_to` before
+    /// rendering. Passed directly to `write_fmt`/`show_source`, `Auto`
+    /// renders as `String`.
+    Auto,
 }
 
-struct Repeated(usize, char);
+#[derive(Clone, Copy)]
+pub struct DisplayOptions {
+    pub mode: DisplayMode,
+    /// Append "For more information, try explaining `<code>`" after an
+    /// error that carries an `ErrorCode`, rustc's `--explain` hint.
+    pub explain_hint: bool,
+}
 
-impl fmt::Display for Repeated {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for _ in 0..self.0 {
-            write!(f, "{}", self.1)?
+impl DisplayOptions {
+    pub const fn new(mode: DisplayMode) -> Self {
+        Self {
+            mode,
+            explain_hint: false,
         }
-        Ok(())
+    }
+
+    pub const fn with_explain_hint(mut self, explain_hint: bool) -> Self {
+        self.explain_hint = explain_hint;
+        self
+    }
+
+    // Replaces `DisplayMode::Auto` with a concrete mode based on `is_tty`;
+    // leaves every other mode untouched.
+    fn resolve_auto(mut self, is_tty: bool) -> Self {
+        if self.mode == DisplayMode::Auto {
+            self.mode = if is_tty {
+                DisplayMode::Terminal
+            } else {
+                DisplayMode::String
+            };
+        }
+        self
     }
 }
 
+/// Cap on how many lines of a single span are rendered before the middle is
+/// elided with a `...` marker, mirroring rustc's snippet emitter.
+pub const DEFAULT_MAX_SPAN_LINES: usize = 6;
+
 impl<'a> SourceInfo<'a> {
-    pub fn new(name: Option<&'a str>, text: &'a str) -> Self {
+    fn new(name: Option<&'a str>, text: &'a str) -> Self {
         let mut newlines = Vec::new();
         for (i, c) in text.char_indices() {
             if c == '\n' {
@@ -41,7 +82,7 @@ impl<'a> SourceInfo<'a> {
         }
     }
 
-    pub fn name(&self) -> &str {
+    fn name(&self) -> &str {
         match &self.name {
             Some(s) => s,
             None => "<none>",
@@ -74,50 +115,255 @@ impl<'a> SourceInfo<'a> {
         }
     }
 
-    pub fn show_source<W: fmt::Write>(
+    // Returns the char index (within the line starting at byte `line_s`)
+    // of the given absolute byte position.
+    fn byte_to_char_in_line(&self, line_s: usize, bytepos: usize) -> usize {
+        self.text[line_s..bytepos].chars().count()
+    }
+
+    // Returns the 1-based line number and 0-based char column of a byte
+    // position.
+    fn line_col(&self, bytepos: usize) -> (usize, usize) {
+        let line = self.line_idx(bytepos);
+        let col = self.text[self.line_start(line)..bytepos].chars().count();
+        (line + 1, col)
+    }
+
+    /// Renders the source lines touched by `spans`, underlining each with
+    /// `^` (primary) or `-` (secondary). A span's label is printed once, on
+    /// the last line the span touches, after that line's full-width
+    /// underline row (labels of several spans follow each other in start
+    /// order); it is not placed directly after the span's own underline
+    /// run. `spans` must all lie in this file;
+    /// `SourceMap::show_source` groups a diagnostic's spans by file before
+    /// calling this. A span covering more than `DEFAULT_MAX_SPAN_LINES`
+    /// lines has its middle elided with a `...` marker, keeping only its
+    /// first and last few lines.
+    fn show_lines<W: fmt::Write>(
         &self,
-        loc: Loc,
+        spans: &[&Span],
         w: &mut W,
         config: DisplayOptions,
     ) -> fmt::Result {
-        let (start_line, end_line) = (self.line_idx(loc.start), self.line_idx(loc.end));
-        let start_char = &self.text[self.line_start(start_line)..loc.start]
-            .chars()
-            .count();
-        writeln!(
-            w,
-            "--> {}:{}:{}",
-            self.name(),
-            start_line + 1,
-            start_char + 1
-        )?;
-        let style = Style::new().bold().underline().fg(Color::Red);
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let start_line = spans
+            .iter()
+            .map(|s| self.line_idx(s.loc.loc.start))
+            .min()
+            .unwrap();
+        let end_line = spans
+            .iter()
+            .map(|s| self.line_idx(s.loc.loc.end))
+            .max()
+            .unwrap();
+
+        let visible: BTreeSet<usize> = spans
+            .iter()
+            .flat_map(|sp| self.visible_lines(sp, DEFAULT_MAX_SPAN_LINES))
+            .collect();
+
+        let mut prev_shown: Option<usize> = None;
         for line in start_line..=end_line {
+            if !visible.contains(&line) {
+                continue;
+            }
             let (s, e) = (self.line_start(line), self.line_end(line));
-            let (hs, he) = (s.max(loc.start), e.min(loc.end));
-            match config {
-                DisplayOptions::String => {
-                    writeln!(w, "{:4>}| {}", line + 1, &self.text[s..e],)?;
-                    writeln!(
-                        w,
-                        "{:4>}| {}{}",
-                        line + 1,
-                        Repeated(hs - s, ' '),
-                        Repeated(he - hs, '^')
-                    )?;
-                }
-                DisplayOptions::Terminal => {
-                    writeln!(
-                        w,
-                        "{:4>}| {}{}{}",
-                        line + 1,
-                        &self.text[s..hs],
-                        style.paint(&self.text[hs..he]),
-                        &self.text[he..e]
-                    )?;
+            // A zero-length span (e.g. an "insertion point" diagnostic at a
+            // line start, or at offset `text.len()` for an EOF error) has no
+            // width to intersect with `[s, e)`, so it needs its own
+            // inclusive check instead of the half-open one below.
+            let mut touching: Vec<&Span> = spans
+                .iter()
+                .copied()
+                .filter(|sp| {
+                    if sp.loc.loc.start == sp.loc.loc.end {
+                        sp.loc.loc.start >= s && sp.loc.loc.start <= e
+                    } else {
+                        sp.loc.loc.start < e && sp.loc.loc.end > s
+                    }
+                })
+                .collect();
+            if touching.is_empty() {
+                continue;
+            }
+            touching.sort_by_key(|sp| sp.loc.loc.start);
+
+            if prev_shown.is_some_and(|prev| line > prev + 1) {
+                writeln!(w, "    | ...")?;
+            }
+            prev_shown = Some(line);
+
+            writeln!(w, "{:4>}| {}", line + 1, &self.text[s..e])?;
+
+            let nchars = self.text[s..e].chars().count();
+            let mut marks = vec![' '; nchars];
+            // Apply secondary spans first so a primary span's `^` wins on overlap.
+            for sp in touching.iter().filter(|sp| !sp.primary) {
+                self.mark_span(s, e, nchars, sp, '-', &mut marks);
+            }
+            for sp in touching.iter().filter(|sp| sp.primary) {
+                self.mark_span(s, e, nchars, sp, '^', &mut marks);
+            }
+
+            let underline: String = marks.into_iter().collect();
+            match config.mode {
+                DisplayMode::Terminal => {
+                    let style = Style::new()
+                        .bold()
+                        .fg(if touching.iter().any(|sp| sp.primary) {
+                            Color::Red
+                        } else {
+                            Color::Blue
+                        });
+                    write!(w, "{:4>}| {}", line + 1, style.paint(underline))?;
                 }
+                _ => write!(w, "{:4>}| {}", line + 1, underline)?,
+            }
+            // Only print a span's label on the last line it touches, so a
+            // secondary span spanning several lines doesn't repeat its label
+            // on every one of them.
+            for sp in touching.iter().filter(|sp| {
+                sp.label.is_some()
+                    && self.line_idx(sp.loc.loc.end.max(sp.loc.loc.start + 1) - 1) == line
+            }) {
+                write!(w, "  {}", sp.label.as_deref().unwrap())?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    // Returns the set of lines of `sp` that should be rendered: every line
+    // if the span is short, or just the first/last halves with the middle
+    // elided if it spans more than `max_lines` lines.
+    fn visible_lines(&self, sp: &Span, max_lines: usize) -> BTreeSet<usize> {
+        let start = self.line_idx(sp.loc.loc.start);
+        let end = self.line_idx(sp.loc.loc.end);
+        if end - start < max_lines {
+            return (start..=end).collect();
+        }
+        let head = max_lines - max_lines / 2;
+        let tail = max_lines / 2;
+        (start..start + head)
+            .chain(end + 1 - tail..=end)
+            .collect()
+    }
+
+    fn mark_span(
+        &self,
+        s: usize,
+        e: usize,
+        nchars: usize,
+        sp: &Span,
+        mark: char,
+        marks: &mut [char],
+    ) {
+        let hs = sp.loc.loc.start.max(s);
+        let he = sp.loc.loc.end.min(e);
+        let chs = self.byte_to_char_in_line(s, hs);
+        let che = self.byte_to_char_in_line(s, he).max(chs + 1).min(nchars);
+        for m in marks.iter_mut().take(che).skip(chs) {
+            *m = mark;
+        }
+    }
+}
+
+fn write_json_string(w: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\r' => write!(w, "\\r")?,
+            '\t' => write!(w, "\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{c}")?,
+        }
+    }
+    write!(w, "\"")
+}
+
+/// A codemap holding every source buffer a `Reporter` might reference.
+/// `Loc`s on their own carry no file identity; a `FileLoc` pairs one with
+/// the `FileId` a `SourceMap` hands back from `add`, so a single diagnostic
+/// can point at spans across several registered files (e.g. an import site
+/// and the definition it pulls in).
+pub struct SourceMap<'a> {
+    id: usize,
+    files: Vec<SourceInfo<'a>>,
+}
+
+/// Hands out a unique id to each `SourceMap`, so a `FileId` it mints can be
+/// checked against the map that's using it.
+static NEXT_MAP_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl<'a> Default for SourceMap<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_MAP_ID.fetch_add(1, Ordering::Relaxed),
+            files: Vec::new(),
+        }
+    }
+
+    /// Registers a source buffer and returns the `FileId` to tag its
+    /// `Loc`s with when reporting diagnostics.
+    pub fn add(&mut self, name: Option<&'a str>, text: &'a str) -> FileId {
+        let id = FileId {
+            map: self.id,
+            index: self.files.len(),
+        };
+        self.files.push(SourceInfo::new(name, text));
+        id
+    }
+
+    fn get(&self, file: FileId) -> &SourceInfo<'a> {
+        assert_eq!(
+            file.map, self.id,
+            "FileId used with a different SourceMap than the one that minted it"
+        );
+        &self.files[file.index]
+    }
+
+    /// Renders the source for every span in `spans`, dispatching each
+    /// file's spans to that file's buffer. The diagnostic's primary span
+    /// picks which file's `--> file:line:col` header is printed first;
+    /// any other files referenced by secondary spans follow, in the order
+    /// their spans first appear.
+    pub fn show_source<W: fmt::Write>(
+        &self,
+        spans: &[Span],
+        w: &mut W,
+        config: DisplayOptions,
+    ) -> fmt::Result {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let primary_file = spans.iter().find(|s| s.primary).unwrap_or(&spans[0]).loc.file;
+
+        let mut files = vec![primary_file];
+        for sp in spans {
+            if !files.contains(&sp.loc.file) {
+                files.push(sp.loc.file);
             }
         }
+
+        for file in files {
+            let group: Vec<&Span> = spans.iter().filter(|sp| sp.loc.file == file).collect();
+            let info = self.get(file);
+            let primary = group.iter().find(|sp| sp.primary).unwrap_or(&group[0]);
+            let (header_line, header_col) = info.line_col(primary.loc.loc.start);
+            writeln!(w, "--> {}:{}:{}", info.name(), header_line, header_col + 1)?;
+            info.show_lines(&group, w, config)?;
+        }
         Ok(())
     }
 
@@ -127,26 +373,86 @@ impl<'a> SourceInfo<'a> {
         m: &Message,
         options: DisplayOptions,
     ) -> fmt::Result {
-        match m {
-            Message::Error(e) => {
-                writeln!(w, "error[{}]: {}", e.code.short, e.message)?;
-                if let Some(loc) = e.loc {
-                    self.show_source(loc, w, options)?;
+        if options.mode == DisplayMode::Json {
+            return self.write_json(w, m);
+        }
+        let color = match m.level {
+            Level::Error => Color::Red,
+            Level::Warning => Color::Yellow,
+            Level::Note => Color::Blue,
+            Level::Help => Color::Green,
+        };
+        match options.mode {
+            DisplayMode::Terminal => {
+                let label = Style::new().bold().fg(color).paint(m.level.name());
+                match m.code {
+                    Some(code) => writeln!(w, "{label}[{}]: {}", code.short, m.message)?,
+                    None => writeln!(w, "{label}: {}", m.message)?,
                 }
             }
-            Message::Info(m) => {
-                writeln!(w, "info: {m}")?;
+            _ => match m.code {
+                Some(code) => writeln!(w, "{}[{}]: {}", m.level.name(), code.short, m.message)?,
+                None => writeln!(w, "{}: {}", m.level.name(), m.message)?,
+            },
+        }
+        self.show_source(&m.spans, w, options)?;
+        if options.explain_hint && m.level == Level::Error {
+            if let Some(code) = m.code {
+                writeln!(w, "For more information, try explaining `{}`", code.short)?;
             }
         }
         Ok(())
     }
 
+    // Serializes a single `Message` as one JSON object, matching rustc's
+    // `--error-format=json` line-oriented output.
+    fn write_json(&self, w: &mut impl fmt::Write, m: &Message) -> fmt::Result {
+        write!(w, "{{\"level\":\"{}\",", m.level.name())?;
+        if let Some(code) = m.code {
+            write!(w, "\"code\":")?;
+            write_json_string(w, code.short)?;
+            write!(w, ",")?;
+        }
+        write!(w, "\"message\":")?;
+        write_json_string(w, &m.message)?;
+        write!(w, ",\"spans\":[")?;
+        for (i, sp) in m.spans.iter().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            let info = self.get(sp.loc.file);
+            let (line_start, col_start) = info.line_col(sp.loc.loc.start);
+            let (line_end, col_end) = info.line_col(sp.loc.loc.end);
+            write!(w, "{{\"file\":")?;
+            write_json_string(w, info.name())?;
+            write!(
+                w,
+                ",\"byte_start\":{},\"byte_end\":{},\"line_start\":{line_start},\"col_start\":{col_start},\"line_end\":{line_end},\"col_end\":{col_end},\"is_primary\":{},\"label\":",
+                sp.loc.loc.start, sp.loc.loc.end, sp.primary,
+            )?;
+            match &sp.label {
+                Some(label) => write_json_string(w, label)?,
+                None => write!(w, "null")?,
+            }
+            write!(w, "}}")?;
+        }
+        writeln!(w, "]}}")
+    }
+
+    /// Writes the long-form explanation for `code`, rustc's
+    /// `--explain E0499`-style output.
+    pub fn write_explain(w: &mut impl fmt::Write, code: crate::ErrorCode) -> fmt::Result {
+        writeln!(w, "{}", code.long)
+    }
+
     pub fn extract_report_to(
         &self,
         w: &mut impl fmt::Write,
         r: Reporter,
         options: DisplayOptions,
     ) -> fmt::Result {
+        // A plain `fmt::Write` has no notion of ttys; `Auto` degrades to `String`.
+        let options = options.resolve_auto(false);
         for m in r.poll().into_iter() {
             self.write_fmt(w, &m, options)?;
         }
@@ -155,10 +461,11 @@ impl<'a> SourceInfo<'a> {
 
     pub fn extract_report_to_io(
         &self,
-        w: &mut impl io::Write,
+        w: &mut (impl io::Write + IsTerminal),
         r: Reporter,
         options: DisplayOptions,
     ) -> io::Result<()> {
+        let options = options.resolve_auto(w.is_terminal());
         let mut buf = String::new();
         for m in r.poll().into_iter() {
             self.write_fmt(&mut buf, &m, options)
@@ -171,8 +478,37 @@ impl<'a> SourceInfo<'a> {
 
     pub fn extract_report_to_string(&self, r: Reporter) -> String {
         let mut out = String::new();
-        self.extract_report_to(&mut out, r, DisplayOptions::String)
+        self.extract_report_to(&mut out, r, DisplayOptions::new(DisplayMode::String))
             .unwrap();
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporter::Span;
+    use crate::{FileLoc, Loc};
+
+    #[test]
+    fn zero_length_span_at_line_start_renders_the_line() {
+        let mut map = SourceMap::new();
+        let file = map.add(None, "abc\ndef");
+        let span = Span::primary(FileLoc::new(file, Loc::new(0, 0)));
+        let mut out = String::new();
+        map.show_source(&[span], &mut out, DisplayOptions::new(DisplayMode::String))
+            .unwrap();
+        assert!(out.contains("abc"), "expected source line, got: {out}");
+    }
+
+    #[test]
+    fn zero_length_span_at_eof_renders_the_last_line() {
+        let mut map = SourceMap::new();
+        let file = map.add(None, "abc\ndef");
+        let span = Span::primary(FileLoc::new(file, Loc::new(7, 7)));
+        let mut out = String::new();
+        map.show_source(&[span], &mut out, DisplayOptions::new(DisplayMode::String))
+            .unwrap();
+        assert!(out.contains("def"), "expected source line, got: {out}");
+    }
+}
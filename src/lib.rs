@@ -5,5 +5,5 @@ pub mod reporter;
 
 pub use codes::ErrorCode;
 pub use lazy_static;
-pub use loc::Loc;
-pub use reporter::Reporter;
+pub use loc::{FileId, FileLoc, Loc};
+pub use reporter::{Level, Reporter};
@@ -14,3 +14,29 @@ impl Loc {
         &source[self.start..self.end]
     }
 }
+
+/// Identifies one source file registered in a `display::SourceMap`. Opaque
+/// outside the crate; only a `SourceMap` can mint one. Tagged with the id of
+/// the map that minted it, so looking a `FileId` up in a different
+/// `SourceMap` than the one that produced it fails loudly instead of
+/// silently returning (or panicking on) the wrong file.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct FileId {
+    pub(crate) map: usize,
+    pub(crate) index: usize,
+}
+
+/// A byte range together with the file it indexes into, so a single
+/// diagnostic can reference locations across several files (e.g. an import
+/// site in one file and the definition it pulls in from another).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileLoc {
+    pub file: FileId,
+    pub loc: Loc,
+}
+
+impl FileLoc {
+    pub fn new(file: FileId, loc: Loc) -> Self {
+        Self { file, loc }
+    }
+}
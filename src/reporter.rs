@@ -1,30 +1,92 @@
-use crate::codes::ErrorCode;
-use crate::loc::Loc;
+use crate::codes::{CodeRegistry, ErrorCode};
+use crate::loc::FileLoc;
 
 use std::cell::Cell;
 use std::{cell::RefCell, rc::Rc};
 
-pub struct Error {
-    pub code: ErrorCode,
-    pub loc: Option<Loc>,
-    pub message: String,
+/// Diagnostic severity, mirroring rustc's `Level`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
 }
 
-impl Error {
-    fn new(code: ErrorCode, loc: Option<Loc>, message: String) -> Self {
-        Self { code, loc, message }
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        }
     }
 }
 
-pub enum Message {
-    Error(Error),
-    Info(String),
+/// One labeled location attached to a diagnostic. A diagnostic can carry
+/// several of these at once (e.g. "expected here" vs "found here"); exactly
+/// one is expected to be marked `primary`.
+pub struct Span {
+    pub loc: FileLoc,
+    pub label: Option<String>,
+    pub primary: bool,
+}
+
+impl Span {
+    pub fn primary(loc: FileLoc) -> Self {
+        Self {
+            loc,
+            label: None,
+            primary: true,
+        }
+    }
+
+    pub fn secondary(loc: FileLoc, label: impl Into<String>) -> Self {
+        Self {
+            loc,
+            label: Some(label.into()),
+            primary: false,
+        }
+    }
+
+    pub fn labeled(loc: FileLoc, label: impl Into<String>, primary: bool) -> Self {
+        Self {
+            loc,
+            label: Some(label.into()),
+            primary,
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub code: Option<ErrorCode>,
+    pub spans: Vec<Span>,
+    pub message: String,
+}
+
+impl Message {
+    fn new(level: Level, spans: Vec<Span>, code: Option<ErrorCode>, message: String) -> Self {
+        Self {
+            level,
+            code,
+            spans,
+            message,
+        }
+    }
+
+    fn single_span(loc: Option<FileLoc>) -> Vec<Span> {
+        loc.into_iter().map(Span::primary).collect()
+    }
 }
 
 #[derive(Clone)]
 pub struct Reporter {
     log: Rc<RefCell<Vec<Message>>>,
     errored: Rc<Cell<bool>>,
+    warned: Rc<Cell<bool>>,
+    codes: Rc<RefCell<CodeRegistry>>,
 }
 
 impl Reporter {
@@ -32,6 +94,8 @@ impl Reporter {
         Self {
             log: Rc::new(RefCell::new(Vec::new())),
             errored: Rc::new(Cell::new(false)),
+            warned: Rc::new(Cell::new(false)),
+            codes: Rc::new(RefCell::new(CodeRegistry::new())),
         }
     }
 
@@ -39,23 +103,77 @@ impl Reporter {
         self.errored.get()
     }
 
-    pub fn error(&self, loc: Loc, code: ErrorCode, message: String) {
+    pub fn warned(&self) -> bool {
+        self.warned.get()
+    }
+
+    /// Looks up the long-form description of a code this reporter has
+    /// previously emitted, for `--explain`-style lookups.
+    pub fn explain(&self, short: &str) -> Option<&'static str> {
+        self.codes.borrow().get(short).map(|c| c.long)
+    }
+
+    /// Every distinct `ErrorCode` emitted through this reporter so far.
+    pub fn codes(&self) -> Vec<ErrorCode> {
+        self.codes.borrow().codes().collect()
+    }
+
+    pub fn error(&self, loc: FileLoc, code: ErrorCode, message: String) {
         self.errored.set(true);
         self.error_option_loc(Some(loc), code, message);
     }
 
     pub fn error_unknown_loc(&self, code: ErrorCode, message: String) {
+        self.errored.set(true);
         self.error_option_loc(None, code, message);
     }
 
-    pub fn error_option_loc(&self, loc: Option<Loc>, code: ErrorCode, message: String) {
-        let e = Error::new(code, loc, message);
-        let m = Message::Error(e);
+    pub fn error_option_loc(&self, loc: Option<FileLoc>, code: ErrorCode, message: String) {
+        self.codes.borrow_mut().record(code);
+        let m = Message::new(Level::Error, Message::single_span(loc), Some(code), message);
+        self.log.borrow_mut().push(m)
+    }
+
+    /// Reports an error pointing at several spans at once, e.g. a
+    /// type-mismatch diagnostic that needs to show both the expected and
+    /// found locations.
+    pub fn error_with_spans(&self, spans: Vec<Span>, code: ErrorCode, message: String) {
+        self.errored.set(true);
+        self.codes.borrow_mut().record(code);
+        let m = Message::new(Level::Error, spans, Some(code), message);
+        self.log.borrow_mut().push(m)
+    }
+
+    pub fn warn(&self, loc: FileLoc, code: ErrorCode, message: String) {
+        self.warned.set(true);
+        self.warn_option_loc(Some(loc), code, message);
+    }
+
+    pub fn warn_unknown_loc(&self, code: ErrorCode, message: String) {
+        self.warned.set(true);
+        self.warn_option_loc(None, code, message);
+    }
+
+    pub fn warn_option_loc(&self, loc: Option<FileLoc>, code: ErrorCode, message: String) {
+        self.codes.borrow_mut().record(code);
+        let m = Message::new(Level::Warning, Message::single_span(loc), Some(code), message);
         self.log.borrow_mut().push(m)
     }
 
-    pub fn info(&self, message: String) {
-        let m = Message::Info(message);
+    pub fn warn_with_spans(&self, spans: Vec<Span>, code: ErrorCode, message: String) {
+        self.warned.set(true);
+        self.codes.borrow_mut().record(code);
+        let m = Message::new(Level::Warning, spans, Some(code), message);
+        self.log.borrow_mut().push(m)
+    }
+
+    pub fn note(&self, loc: Option<FileLoc>, message: String) {
+        let m = Message::new(Level::Note, Message::single_span(loc), None, message);
+        self.log.borrow_mut().push(m);
+    }
+
+    pub fn help(&self, loc: Option<FileLoc>, message: String) {
+        let m = Message::new(Level::Help, Message::single_span(loc), None, message);
         self.log.borrow_mut().push(m);
     }
 